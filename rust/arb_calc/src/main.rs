@@ -1,31 +1,620 @@
+use serde::de::Deserializer as _;
 use serde::{Deserialize, Serialize};
-use std::io::{self, BufRead};
+use std::cmp::Ordering;
+use std::io::{self, BufRead, Read, Write};
+
+/// Unsigned 256-bit integer stored as four little-endian `u64` limbs.
+///
+/// Token amounts (raw Solana/EVM base units) routinely exceed `i128::MAX`,
+/// so every amount on the wire is parsed into one of these instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct U256([u64; 4]);
+
+impl U256 {
+    const ZERO: U256 = U256([0, 0, 0, 0]);
+
+    /// Parses an unsigned decimal string, rejecting anything that doesn't
+    /// fit in 256 bits instead of panicking or wrapping.
+    fn from_decimal_str(s: &str) -> Result<U256, String> {
+        if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(format!("not an unsigned decimal integer: {s}"));
+        }
+        let mut v = U256::ZERO;
+        for c in s.chars() {
+            let digit = c.to_digit(10).unwrap() as u64;
+            if v.mul10_add(digit) {
+                return Err(format!("value exceeds 256 bits: {s}"));
+            }
+        }
+        Ok(v)
+    }
+
+    /// `self = self * 10 + digit`, returning `true` on overflow past 256 bits.
+    fn mul10_add(&mut self, digit: u64) -> bool {
+        let mut carry = digit as u128;
+        for limb in self.0.iter_mut() {
+            let v = (*limb as u128) * 10 + carry;
+            *limb = v as u64;
+            carry = v >> 64;
+        }
+        carry != 0
+    }
+
+    fn checked_add(self, other: U256) -> Option<U256> {
+        let mut out = [0u64; 4];
+        let mut carry = 0u128;
+        for ((&a, &b), out_limb) in self.0.iter().zip(other.0.iter()).zip(out.iter_mut()) {
+            let sum = a as u128 + b as u128 + carry;
+            *out_limb = sum as u64;
+            carry = sum >> 64;
+        }
+        (carry == 0).then_some(U256(out))
+    }
+
+    fn checked_sub(self, other: U256) -> Option<U256> {
+        if self < other {
+            return None;
+        }
+        let mut out = [0u64; 4];
+        let mut borrow = 0i128;
+        for ((&a, &b), out_limb) in self.0.iter().zip(other.0.iter()).zip(out.iter_mut()) {
+            let diff = a as i128 - b as i128 - borrow;
+            if diff < 0 {
+                *out_limb = (diff + (1i128 << 64)) as u64;
+                borrow = 1;
+            } else {
+                *out_limb = diff as u64;
+                borrow = 0;
+            }
+        }
+        Some(U256(out))
+    }
+
+    fn to_decimal_string(self) -> String {
+        if self == U256::ZERO {
+            return "0".to_string();
+        }
+        let mut limbs = self.0;
+        let mut digits = Vec::new();
+        while limbs != [0u64; 4] {
+            let mut rem: u128 = 0;
+            for limb in limbs.iter_mut().rev() {
+                let cur = (rem << 64) | *limb as u128;
+                *limb = (cur / 10) as u64;
+                rem = cur % 10;
+            }
+            digits.push(std::char::from_digit(rem as u32, 10).unwrap());
+        }
+        digits.iter().rev().collect()
+    }
+}
+
+impl PartialOrd for U256 {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for U256 {
+    fn cmp(&self, other: &Self) -> Ordering {
+        for i in (0..4).rev() {
+            match self.0[i].cmp(&other.0[i]) {
+                Ordering::Equal => continue,
+                ord => return ord,
+            }
+        }
+        Ordering::Equal
+    }
+}
+
+/// Signed 256-bit integer, represented as sign + `U256` magnitude so profit
+/// figures can go negative without needing a wider two's-complement type.
+/// `magnitude == 0` always normalizes to `negative == false`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct I256 {
+    negative: bool,
+    magnitude: U256,
+}
+
+impl I256 {
+    fn to_decimal_string(self) -> String {
+        if self.negative {
+            format!("-{}", self.magnitude.to_decimal_string())
+        } else {
+            self.magnitude.to_decimal_string()
+        }
+    }
+}
+
+impl PartialOrd for I256 {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for I256 {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, false) => self.magnitude.cmp(&other.magnitude),
+            (true, true) => other.magnitude.cmp(&self.magnitude),
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+        }
+    }
+}
+
+fn parse_u256(s: &str) -> Result<U256, String> {
+    U256::from_decimal_str(s.trim())
+}
+
+fn parse_i256(s: &str) -> Result<I256, String> {
+    let s = s.trim();
+    match s.strip_prefix('-') {
+        Some(rest) => {
+            let magnitude = parse_u256(rest)?;
+            Ok(I256 {
+                negative: magnitude != U256::ZERO,
+                magnitude,
+            })
+        }
+        None => Ok(I256 {
+            negative: false,
+            magnitude: parse_u256(s)?,
+        }),
+    }
+}
+
+fn u256_from_str<'de, D>(deserializer: D) -> Result<U256, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    parse_u256(&s).map_err(serde::de::Error::custom)
+}
+
+fn i256_from_str<'de, D>(deserializer: D) -> Result<I256, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    parse_i256(&s).map_err(serde::de::Error::custom)
+}
+
+/// Like `u256_from_str`, but for a field that's absent on older callers who
+/// don't quote the chained route.
+fn opt_u256_from_str<'de, D>(deserializer: D) -> Result<Option<U256>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    match Option::<String>::deserialize(deserializer)? {
+        Some(s) => parse_u256(&s).map(Some).map_err(serde::de::Error::custom),
+        None => Ok(None),
+    }
+}
+
+/// Computes `a - b - c` as a signed value, promoting to `I256` so an
+/// underflow (more common than it sounds once fees exceed output) is a
+/// negative result instead of a panic.
+fn sub3_signed(a: U256, b: U256, c: U256) -> Result<I256, String> {
+    let bc = b
+        .checked_add(c)
+        .ok_or_else(|| "amountIn + fee overflows 256 bits".to_string())?;
+    match a.checked_sub(bc) {
+        Some(magnitude) => Ok(I256 {
+            negative: false,
+            magnitude,
+        }),
+        None => {
+            let magnitude = bc
+                .checked_sub(a)
+                .expect("bc > a since a.checked_sub(bc) failed");
+            Ok(I256 {
+                negative: magnitude != U256::ZERO,
+                magnitude,
+            })
+        }
+    }
+}
 
 #[derive(Debug, Deserialize)]
+#[allow(non_snake_case)]
 struct Request {
-    amountIn: String,
-    quote1Out: String,
-    quote1MinOut: String,
-    quote2Out: String,
-    quote2MinOut: String,
-    minProfit: String,
-    #[serde(alias = "feeEstimateLamports")]
-    feeEstimateInInputUnits: String,
+    #[serde(deserialize_with = "u256_from_str")]
+    amountIn: U256,
+    #[serde(deserialize_with = "u256_from_str")]
+    quote1Out: U256,
+    #[serde(deserialize_with = "u256_from_str")]
+    quote1MinOut: U256,
+    #[serde(deserialize_with = "u256_from_str")]
+    quote2Out: U256,
+    #[serde(deserialize_with = "u256_from_str")]
+    quote2MinOut: U256,
+    #[serde(deserialize_with = "i256_from_str")]
+    minProfit: I256,
+    #[serde(alias = "feeEstimateLamports", deserialize_with = "u256_from_str")]
+    feeEstimateInInputUnits: U256,
+    /// Quote2's output and min-out *as quoted with quote1's output as its
+    /// input amount* — i.e. the real second leg of a quote1-then-quote2
+    /// route. Optional because older callers don't send it; when absent we
+    /// only evaluate the two single-hop routes.
+    #[serde(default, deserialize_with = "opt_u256_from_str")]
+    quote1ThenQuote2Out: Option<U256>,
+    #[serde(default, deserialize_with = "opt_u256_from_str")]
+    quote1ThenQuote2MinOut: Option<U256>,
+}
+
+#[derive(Debug, Serialize)]
+#[allow(non_snake_case)]
+struct RouteResult {
+    route: String,
+    profit: String,
+    conservativeProfit: String,
+    profitable: bool,
 }
 
 #[derive(Debug, Serialize)]
+#[allow(non_snake_case)]
 struct Response {
     profitable: bool,
     profit: String,
     conservativeProfit: String,
+    route: String,
+    routes: Vec<RouteResult>,
+    // No `skip_serializing_if` here: `--binary` postcard-encodes this same
+    // struct, and postcard's fixed, non-self-describing layout needs every
+    // field's bytes (including the `None` tag) always written, or decoding
+    // a successful response back into `Response` fails.
+    error: Option<String>,
+}
+
+/// A `Response`-shaped stand-in for a line the lenient NDJSON reader
+/// couldn't parse, so one bad record doesn't kill the whole stream.
+fn error_response(message: String) -> Response {
+    Response {
+        profitable: false,
+        profit: "0".to_string(),
+        conservativeProfit: "0".to_string(),
+        route: String::new(),
+        routes: Vec::new(),
+        error: Some(message),
+    }
 }
 
-fn parse_i128(s: &str) -> Result<i128, String> {
-    s.parse::<i128>().map_err(|e| format!("invalid int: {s}: {e}"))
+struct RouteEval {
+    route: &'static str,
+    profit: I256,
+    conservative_profit: I256,
 }
 
-fn main() {
+fn evaluate_route(
+    route: &'static str,
+    out: U256,
+    min_out: U256,
+    amount_in: U256,
+    fee: U256,
+) -> Result<RouteEval, String> {
+    Ok(RouteEval {
+        route,
+        profit: sub3_signed(out, amount_in, fee)?,
+        conservative_profit: sub3_signed(min_out, amount_in, fee)?,
+    })
+}
+
+/// Evaluates every route leg and picks the best one by conservativeProfit.
+///
+/// `quote1` and `quote2` are each evaluated as a standalone single-hop
+/// route. `quote1_then_quote2` is included only when the caller sends
+/// `quote1ThenQuote2Out`/`quote1ThenQuote2MinOut` — quote2's output as
+/// actually quoted with quote1's output as its input, i.e. a real two-hop
+/// result rather than a reused single-hop quote. The fee is paid twice,
+/// once per hop.
+fn evaluate(req: Request) -> Result<Response, String> {
+    let fee = req.feeEstimateInInputUnits;
+
+    let mut routes = vec![
+        evaluate_route("quote1", req.quote1Out, req.quote1MinOut, req.amountIn, fee)?,
+        evaluate_route("quote2", req.quote2Out, req.quote2MinOut, req.amountIn, fee)?,
+    ];
+
+    if let (Some(chain_out), Some(chain_min_out)) =
+        (req.quote1ThenQuote2Out, req.quote1ThenQuote2MinOut)
+    {
+        let two_hop_fee = fee
+            .checked_add(fee)
+            .ok_or_else(|| "feeEstimateInInputUnits doubled overflows 256 bits".to_string())?;
+        routes.push(evaluate_route(
+            "quote1_then_quote2",
+            chain_out,
+            chain_min_out,
+            req.amountIn,
+            two_hop_fee,
+        )?);
+    }
+
+    let best = routes.iter().fold(&routes[0], |best, r| {
+        if r.conservative_profit > best.conservative_profit {
+            r
+        } else {
+            best
+        }
+    });
+
+    let route_results = routes
+        .iter()
+        .map(|r| RouteResult {
+            route: r.route.to_string(),
+            profit: r.profit.to_decimal_string(),
+            conservativeProfit: r.conservative_profit.to_decimal_string(),
+            profitable: r.conservative_profit >= req.minProfit,
+        })
+        .collect();
+
+    Ok(Response {
+        profitable: best.conservative_profit >= req.minProfit,
+        profit: best.profit.to_decimal_string(),
+        conservativeProfit: best.conservative_profit.to_decimal_string(),
+        route: best.route.to_string(),
+        routes: route_results,
+        error: None,
+    })
+}
+
+fn print_response(res: &Response) {
+    println!("{}", serde_json::to_string(res).unwrap());
+}
+
+fn emit(req: Request) {
+    let res = match evaluate(req) {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    };
+    print_response(&res);
+}
+
+/// Like `emit`, but for `--lenient`: a computation error (e.g. an overflow
+/// in `sub3_signed`) becomes an error `Response` instead of killing the
+/// process, consistent with how `run_ndjson_lenient` already handles a
+/// malformed line.
+fn emit_lenient(req: Request) {
+    let res = match evaluate(req) {
+        Ok(v) => v,
+        Err(e) => error_response(e),
+    };
+    print_response(&res);
+}
+
+/// Whether this build/target can actually take the SIMD-accelerated path.
+/// Runtime CPU feature detection (not `cfg!(target_feature = ...)`, which
+/// only reflects what the compiler was told to target at build time and is
+/// `false` for a normal build even on an AVX2-capable machine).
+fn simd_available() -> bool {
+    #[cfg(target_arch = "x86_64")]
+    {
+        is_x86_feature_detected!("avx2")
+    }
+    #[cfg(target_arch = "aarch64")]
+    {
+        std::arch::is_aarch64_feature_detected!("neon")
+    }
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    {
+        false
+    }
+}
+
+/// Peeks the first non-whitespace byte of `reader` without consuming it,
+/// used to tell NDJSON input (one object per line) apart from a single
+/// top-level JSON array.
+fn peek_first_non_ws<R: BufRead>(reader: &mut R) -> io::Result<Option<u8>> {
+    loop {
+        let len = {
+            let buf = reader.fill_buf()?;
+            if buf.is_empty() {
+                return Ok(None);
+            }
+            if !buf[0].is_ascii_whitespace() {
+                return Ok(Some(buf[0]));
+            }
+            1
+        };
+        reader.consume(len);
+    }
+}
+
+/// Default and fallback path: serde_json, used by default and when `--fast`
+/// is requested on a target without AVX2/NEON. Sniffs the first
+/// non-whitespace byte to decide between NDJSON (one `Request` per line)
+/// and a single streamed top-level JSON array.
+fn run_serde_json(lenient: bool) {
+    let stdin = io::stdin();
+    let mut reader = stdin.lock();
+
+    let first = match peek_first_non_ws(&mut reader) {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    };
+
+    match first {
+        None => {}
+        Some(b'[') => run_array_stream(reader, lenient),
+        Some(_) if lenient => run_ndjson_lenient(reader),
+        Some(_) => run_ndjson_stream(reader),
+    }
+}
+
+/// Streams whitespace-separated top-level values straight off the reader,
+/// one `Request` at a time, never buffering more than one object.
+fn run_ndjson_stream<R: io::Read>(reader: R) {
+    for req in serde_json::Deserializer::from_reader(reader).into_iter::<Request>() {
+        let req = match req {
+            Ok(v) => v,
+            Err(e) => {
+                eprintln!("{e}");
+                std::process::exit(1);
+            }
+        };
+        emit(req);
+    }
+}
+
+/// Strips `//` and `/* */` comments and trailing commas before `}`/`]` from
+/// a single JSON value, in the spirit of serde_jsonrc's relaxations, so
+/// hand-edited or templated quote payloads still parse. Quote-aware, so it
+/// won't touch `//` or `,` that appear inside a JSON string.
+fn strip_lenient(input: &str) -> String {
+    let chars: Vec<char> = input.chars().collect();
+    let mut out = String::with_capacity(input.len());
+    let mut in_string = false;
+    let mut escape = false;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+
+        if in_string {
+            out.push(c);
+            if escape {
+                escape = false;
+            } else if c == '\\' {
+                escape = true;
+            } else if c == '"' {
+                in_string = false;
+            }
+            i += 1;
+            continue;
+        }
+
+        if c == '"' {
+            in_string = true;
+            out.push(c);
+            i += 1;
+        } else if c == '/' && chars.get(i + 1) == Some(&'/') {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+        } else if c == '/' && chars.get(i + 1) == Some(&'*') {
+            i += 2;
+            while i + 1 < chars.len() && !(chars[i] == '*' && chars[i + 1] == '/') {
+                i += 1;
+            }
+            i = (i + 2).min(chars.len());
+        } else if c == ',' {
+            let mut j = i + 1;
+            while j < chars.len() && chars[j].is_whitespace() {
+                j += 1;
+            }
+            if !matches!(chars.get(j), Some('}') | Some(']')) {
+                out.push(c);
+            }
+            i += 1;
+        } else {
+            out.push(c);
+            i += 1;
+        }
+    }
+
+    out
+}
+
+/// Opt-in tolerant NDJSON reader for `--lenient`: strips comments/trailing
+/// commas from each line and, unlike `run_ndjson_stream`, never aborts the
+/// process on a malformed record — it emits a `Response`-shaped error
+/// object for that line and keeps going, so one bad record in a batch
+/// doesn't take down the rest of the stream.
+fn run_ndjson_lenient<R: BufRead>(mut reader: R) {
+    let mut raw = String::new();
+    loop {
+        raw.clear();
+        let read = match reader.read_line(&mut raw) {
+            Ok(n) => n,
+            Err(e) => {
+                eprintln!("{e}");
+                std::process::exit(1);
+            }
+        };
+        if read == 0 {
+            break;
+        }
+
+        let trimmed = raw.trim();
+        if trimmed.is_empty() {
+            continue;
+        }
+
+        let cleaned = strip_lenient(trimmed);
+        match serde_json::from_str::<Request>(&cleaned) {
+            Ok(req) => emit_lenient(req),
+            Err(e) => print_response(&error_response(e.to_string())),
+        }
+    }
+}
+
+/// Streams a single top-level JSON array element-by-element via `SeqAccess`
+/// so a 100k-element batch never has to be held in memory at once.
+///
+/// Under `--lenient`, each element is first pulled out as a `RawValue` (so a
+/// single element only needs to be syntactically valid JSON, not a valid
+/// `Request`) and then cleaned and parsed the same way `run_ndjson_lenient`
+/// handles a bad line: a failure becomes an error `Response` and the array
+/// keeps streaming instead of aborting on the first bad element.
+fn run_array_stream<R: io::Read>(reader: R, lenient: bool) {
+    struct StreamVisitor {
+        lenient: bool,
+    }
+
+    impl<'de> serde::de::Visitor<'de> for StreamVisitor {
+        type Value = ();
+
+        fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            f.write_str("a JSON array of requests")
+        }
+
+        fn visit_seq<A>(self, mut seq: A) -> Result<(), A::Error>
+        where
+            A: serde::de::SeqAccess<'de>,
+        {
+            if self.lenient {
+                while let Some(raw) = seq.next_element::<Box<serde_json::value::RawValue>>()? {
+                    let cleaned = strip_lenient(raw.get());
+                    match serde_json::from_str::<Request>(&cleaned) {
+                        Ok(req) => emit_lenient(req),
+                        Err(e) => print_response(&error_response(e.to_string())),
+                    }
+                }
+            } else {
+                while let Some(req) = seq.next_element::<Request>()? {
+                    emit(req);
+                }
+            }
+            Ok(())
+        }
+    }
+
+    let mut de = serde_json::Deserializer::from_reader(reader);
+    if let Err(e) = de.deserialize_seq(StreamVisitor { lenient }) {
+        eprintln!("{e}");
+        std::process::exit(1);
+    }
+}
+
+/// SIMD-accelerated fast path for `--fast`: each line is read by `lines()`
+/// into a new `String` and then copied into a byte buffer (simd_json
+/// deserializes in place). The byte buffer is reused across lines; the
+/// per-line `String` is not.
+///
+/// Under `--lenient`, the line is run through `strip_lenient` before being
+/// copied into the buffer, and a parse or `evaluate` failure becomes an
+/// error `Response` instead of killing the process, matching
+/// `run_ndjson_lenient`/`run_array_stream`.
+fn run_fast(lenient: bool) {
     let stdin = io::stdin();
+    let mut buf: Vec<u8> = Vec::new();
     for line in stdin.lock().lines() {
         let line = match line {
             Ok(l) => l,
@@ -39,60 +628,306 @@ fn main() {
             continue;
         }
 
-        let req: Request = match serde_json::from_str(&line) {
+        buf.clear();
+        if lenient {
+            buf.extend_from_slice(strip_lenient(line.trim()).as_bytes());
+        } else {
+            buf.extend_from_slice(line.as_bytes());
+        }
+
+        let req: Request = match simd_json::from_slice(&mut buf) {
             Ok(v) => v,
+            Err(e) if lenient => {
+                print_response(&error_response(e.to_string()));
+                continue;
+            }
             Err(e) => {
                 eprintln!("{e}");
                 std::process::exit(1);
             }
         };
 
-        let amount_in = match parse_i128(&req.amountIn) {
+        if lenient {
+            emit_lenient(req);
+        } else {
+            emit(req);
+        }
+    }
+}
+
+/// Reads one 4-byte little-endian length-prefixed postcard `Request` and
+/// writes back a length-prefixed postcard `Response`, for callers that spawn
+/// this binary as a co-located decision oracle and want to skip JSON
+/// encode/decode on the hot path. Same structs, same `evaluate`, just a
+/// different codec.
+fn run_binary() {
+    let mut stdin = io::stdin();
+    let mut stdout = io::stdout();
+    let mut len_buf = [0u8; 4];
+
+    loop {
+        match stdin.read_exact(&mut len_buf) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(e) => {
+                eprintln!("{e}");
+                std::process::exit(1);
+            }
+        }
+        let len = u32::from_le_bytes(len_buf) as usize;
+
+        let mut body = vec![0u8; len];
+        if let Err(e) = stdin.read_exact(&mut body) {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+
+        let req: Request = match postcard::from_bytes(&body) {
             Ok(v) => v,
             Err(e) => {
                 eprintln!("{e}");
                 std::process::exit(1);
             }
         };
-        let out = match parse_i128(&req.quote2Out) {
+
+        let res = match evaluate(req) {
             Ok(v) => v,
             Err(e) => {
                 eprintln!("{e}");
                 std::process::exit(1);
             }
         };
-        let out_min = match parse_i128(&req.quote2MinOut) {
+
+        let encoded: Vec<u8> = match postcard::to_allocvec(&res) {
             Ok(v) => v,
             Err(e) => {
                 eprintln!("{e}");
                 std::process::exit(1);
             }
         };
-    let min_profit = match parse_i128(&req.minProfit) {
-        Ok(v) => v,
-        Err(e) => {
-            eprintln!("{e}");
-            std::process::exit(1);
-        }
-    };
-    let fee_estimate = match parse_i128(&req.feeEstimateInInputUnits) {
-        Ok(v) => v,
-        Err(e) => {
+
+        let write_result = stdout
+            .write_all(&(encoded.len() as u32).to_le_bytes())
+            .and_then(|()| stdout.write_all(&encoded))
+            .and_then(|()| stdout.flush());
+        if let Err(e) = write_result {
             eprintln!("{e}");
             std::process::exit(1);
         }
-    };
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let binary_requested = args.iter().any(|a| a == "--binary");
+    let fast_requested = args.iter().any(|a| a == "--fast");
+    let lenient = args.iter().any(|a| a == "--lenient");
 
-    let profit = out - amount_in - fee_estimate;
-    let conservative_profit = out_min - amount_in - fee_estimate;
-        let profitable = conservative_profit >= min_profit;
+    if binary_requested {
+        run_binary();
+    } else if !fast_requested {
+        run_serde_json(lenient);
+    } else if simd_available() {
+        run_fast(lenient);
+    } else {
+        eprintln!("--fast requested but no AVX2/NEON support detected on this target; falling back to serde_json");
+        run_serde_json(lenient);
+    }
+}
 
-        let res = Response {
-            profitable,
-            profit: profit.to_string(),
-            conservativeProfit: conservative_profit.to_string(),
-        };
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const U256_MAX: &str =
+        "115792089237316195423570985008687907853269984665640564039457584007913129639935";
+
+    #[test]
+    fn parses_small_decimal() {
+        assert_eq!(parse_u256("42").unwrap().to_decimal_string(), "42");
+    }
+
+    #[test]
+    fn parses_zero() {
+        assert_eq!(parse_u256("0").unwrap().to_decimal_string(), "0");
+    }
+
+    #[test]
+    fn round_trips_u256_max() {
+        assert_eq!(parse_u256(U256_MAX).unwrap().to_decimal_string(), U256_MAX);
+    }
+
+    #[test]
+    fn rejects_value_one_past_u256_max() {
+        // U256_MAX + 1 == 2^256, one digit carry past the last limb.
+        let one_past =
+            "115792089237316195423570985008687907853269984665640564039457584007913129639936";
+        assert!(parse_u256(one_past).is_err());
+    }
+
+    #[test]
+    fn rejects_non_digit_input() {
+        assert!(parse_u256("12a").is_err());
+        assert!(parse_u256("-1").is_err());
+        assert!(parse_u256("").is_err());
+    }
+
+    #[test]
+    fn checked_add_carries_across_limb_boundaries() {
+        // u64::MAX in the low limb plus 1 must carry into the second limb.
+        let a = parse_u256("18446744073709551615").unwrap(); // u64::MAX
+        let b = parse_u256("1").unwrap();
+        assert_eq!(
+            a.checked_add(b).unwrap().to_decimal_string(),
+            "18446744073709551616"
+        );
+    }
+
+    #[test]
+    fn checked_add_overflows_past_u256_max() {
+        let max = parse_u256(U256_MAX).unwrap();
+        let one = parse_u256("1").unwrap();
+        assert!(max.checked_add(one).is_none());
+    }
+
+    #[test]
+    fn checked_sub_borrows_across_limb_boundaries() {
+        let a = parse_u256("18446744073709551616").unwrap(); // u64::MAX + 1
+        let b = parse_u256("1").unwrap();
+        assert_eq!(
+            a.checked_sub(b).unwrap().to_decimal_string(),
+            "18446744073709551615"
+        );
+    }
+
+    #[test]
+    fn checked_sub_underflow_is_none() {
+        let a = parse_u256("1").unwrap();
+        let b = parse_u256("2").unwrap();
+        assert!(a.checked_sub(b).is_none());
+    }
+
+    #[test]
+    fn parse_i256_normalizes_negative_zero() {
+        let v = parse_i256("-0").unwrap();
+        assert!(!v.negative);
+        assert_eq!(v.to_decimal_string(), "0");
+    }
+
+    #[test]
+    fn parse_i256_roundtrips_sign() {
+        assert_eq!(parse_i256("-5").unwrap().to_decimal_string(), "-5");
+        assert_eq!(parse_i256("5").unwrap().to_decimal_string(), "5");
+    }
+
+    #[test]
+    fn i256_orders_by_sign_then_magnitude() {
+        let neg_big = parse_i256("-100").unwrap();
+        let neg_small = parse_i256("-1").unwrap();
+        let zero = parse_i256("0").unwrap();
+        let pos = parse_i256("1").unwrap();
+        assert!(neg_big < neg_small);
+        assert!(neg_small < zero);
+        assert!(zero < pos);
+    }
+
+    #[test]
+    fn sub3_signed_goes_negative_on_underflow() {
+        let out = parse_u256("10").unwrap();
+        let amount_in = parse_u256("50").unwrap();
+        let fee = parse_u256("5").unwrap();
+        let profit = sub3_signed(out, amount_in, fee).unwrap();
+        assert_eq!(profit.to_decimal_string(), "-45");
+    }
+
+    #[test]
+    fn sub3_signed_stays_positive() {
+        let out = parse_u256("100").unwrap();
+        let amount_in = parse_u256("50").unwrap();
+        let fee = parse_u256("5").unwrap();
+        let profit = sub3_signed(out, amount_in, fee).unwrap();
+        assert_eq!(profit.to_decimal_string(), "45");
+    }
+
+    fn request(json: &str) -> Request {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn evaluate_picks_the_route_with_the_best_conservative_profit() {
+        // quote1 has the higher headline profit but a much worse min-out,
+        // so quote2 should win on conservativeProfit.
+        let req = request(
+            r#"{
+                "amountIn": "100",
+                "quote1Out": "200",
+                "quote1MinOut": "50",
+                "quote2Out": "150",
+                "quote2MinOut": "140",
+                "minProfit": "0",
+                "feeEstimateInInputUnits": "1"
+            }"#,
+        );
+        let res = evaluate(req).unwrap();
+        assert_eq!(res.route, "quote2");
+        assert_eq!(res.conservativeProfit, "39");
+    }
+
+    #[test]
+    fn evaluate_skips_chained_route_when_fields_absent() {
+        let req = request(
+            r#"{
+                "amountIn": "100",
+                "quote1Out": "110",
+                "quote1MinOut": "90",
+                "quote2Out": "105",
+                "quote2MinOut": "95",
+                "minProfit": "0",
+                "feeEstimateInInputUnits": "1"
+            }"#,
+        );
+        let res = evaluate(req).unwrap();
+        assert_eq!(res.routes.len(), 2);
+        assert!(res.routes.iter().all(|r| r.route != "quote1_then_quote2"));
+    }
+
+    #[test]
+    fn evaluate_includes_and_can_select_the_chained_route() {
+        // quote1ThenQuote2Out is quoted with quote1Out as the chain's input,
+        // so the real two-hop route clears both single-hop routes even
+        // after paying the fee twice.
+        let req = request(
+            r#"{
+                "amountIn": "100",
+                "quote1Out": "110",
+                "quote1MinOut": "105",
+                "quote2Out": "108",
+                "quote2MinOut": "104",
+                "minProfit": "0",
+                "feeEstimateInInputUnits": "1",
+                "quote1ThenQuote2Out": "130",
+                "quote1ThenQuote2MinOut": "125"
+            }"#,
+        );
+        let res = evaluate(req).unwrap();
+        assert_eq!(res.routes.len(), 3);
+        assert_eq!(res.route, "quote1_then_quote2");
+        // conservativeProfit = 125 - 100 - (1 + 1) = 23
+        assert_eq!(res.conservativeProfit, "23");
+    }
 
-        println!("{}", serde_json::to_string(&res).unwrap());
+    #[test]
+    fn strip_lenient_drops_comments_and_trailing_commas_outside_strings() {
+        let input = r#"{
+            // a line comment
+            "amountIn": "100", /* inline comment */
+            "note": "keep // this and , this inside the string",
+            "trailing": "comma",
+        }"#;
+        let cleaned = strip_lenient(input);
+        let parsed: serde_json::Value = serde_json::from_str(&cleaned).unwrap();
+        assert_eq!(parsed["amountIn"], "100");
+        assert_eq!(parsed["note"], "keep // this and , this inside the string");
+        assert_eq!(parsed["trailing"], "comma");
     }
 }